@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+root="$(cd -- "$(dirname -- "${BASH_SOURCE[0]}")/../.." && pwd)"
+cd "$root/test/fixtures/complex"
+
+"$root/f" -wOGn 'a.b' globdot | sort
+
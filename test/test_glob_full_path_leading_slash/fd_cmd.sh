@@ -0,0 +1,7 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+cd "$(dirname -- "${BASH_SOURCE[0]}")/../fixtures/complex"
+
+fd --glob --full-path '/top/*' | sort
+
@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+root="$(cd -- "$(dirname -- "${BASH_SOURCE[0]}")/../.." && pwd)"
+tmp="$(mktemp -d)"
+trap 'rm -rf "$tmp"' EXIT
+
+# git can't track an empty directory, so make one in a copy of the fixture.
+cp -R "$root/test/fixtures/complex/empty" "$tmp/"
+mkdir -p "$tmp/empty/empty_dir"
+cd "$tmp"
+
+"$root/f" -wOGnr -t e . empty | sort
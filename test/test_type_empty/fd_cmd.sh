@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+fixture="$(cd -- "$(dirname -- "${BASH_SOURCE[0]}")/../fixtures/complex" && pwd)"
+tmp="$(mktemp -d)"
+trap 'rm -rf "$tmp"' EXIT
+
+# git can't track an empty directory, so make one in a copy of the fixture.
+cp -R "$fixture/empty" "$tmp/"
+mkdir -p "$tmp/empty/empty_dir"
+cd "$tmp"
+
+fd -t e . empty | sort